@@ -3,21 +3,132 @@ MLX Whisper transcription script for macOS
 Uses Apple's MLX framework for fast, efficient transcription.
 """
 
+import argparse
+import importlib.metadata
 import json
 import os
 import sys
-from typing import Dict, List, Union
+from typing import Any, Dict, List, Optional
 import io
 from contextlib import redirect_stdout, redirect_stderr
 
 import mlx_whisper
+import tqdm
 
+# Printed on its own line right before the JSON payload so callers can split
+# on it instead of guessing where the JSON starts in stdout.
+JSON_SENTINEL = "===JSON==="
 
-def transcribe_audio(audio_path: str) -> int:
+DIARIZATION_MODEL = "pyannote/speaker-diarization-3.1"
+# assign_speakers uses the pyannote.audio 3.x API (use_auth_token=, Annotation
+# results); 4.x renamed the keyword and wraps the pipeline output.
+PYANNOTE_REQUIREMENT = "pyannote.audio>=3.1,<4"
+# Diarization on CPU takes roughly as long as decoding, so with --diarize the
+# decode pass only advances PROGRESS to this point.
+DECODE_SPAN_WITH_DIARIZATION = 0.5
+
+
+class ProgressBar(tqdm.tqdm):
+    """tqdm bar that also reports ``PROGRESS <fraction>`` lines on stderr.
+
+    mlx_whisper advances a ``tqdm.tqdm`` bar as it decodes; its own output is
+    swallowed by the stderr capture, so progress goes to the real stderr.
+    """
+
+    # Share of the overall 0-1 range covered by decoding; the remainder is
+    # left for later stages such as diarization.
+    span = 1.0
+
+    def update(self, n=1):
+        displayed = super().update(n)
+        if self.total:
+            fraction = min(self.n / self.total, 1.0) * self.span
+            print(f"PROGRESS {fraction:.2f}", file=sys.__stderr__, flush=True)
+        return displayed
+
+
+def check_diarization_requirements() -> Optional[str]:
+    """Returns why diarization can't run, or None if its prerequisites are met.
+
+    Checks for a 3.x ``pyannote.audio`` install and a token in ``HF_TOKEN``.
+    Whether the token may access the gated model is only known once
+    ``assign_speakers`` tries to load it.
+    """
+    install_hint = f"pip install '{PYANNOTE_REQUIREMENT}'"
+    try:
+        version = importlib.metadata.version("pyannote.audio")
+    except importlib.metadata.PackageNotFoundError:
+        return f"--diarize requires {PYANNOTE_REQUIREMENT} ({install_hint})"
+    if not version.startswith("3."):
+        return (
+            f"--diarize requires {PYANNOTE_REQUIREMENT}, found {version} "
+            f"({install_hint})"
+        )
+
+    if not os.environ.get("HF_TOKEN"):
+        return (
+            "--diarize requires a Hugging Face token in HF_TOKEN with access to "
+            f"the gated {DIARIZATION_MODEL} model"
+        )
+
+    return None
+
+
+def assign_speakers(audio_path: str, segments: List[Dict[str, Any]]) -> None:
+    """Labels each segment with the speaker that overlaps it the most.
+
+    Runs pyannote speaker diarization over the audio. Callers should check
+    ``check_diarization_requirements`` first.
+    Segments that overlap no speaker turn are left without a ``speaker`` key.
+
+    Raises:
+        RuntimeError: If the diarization model can't be loaded.
+    """
+    from pyannote.audio import Pipeline
+
+    # pyannote returns None (and prints the reason to stdout) instead of
+    # raising when the token is rejected or the gated model isn't accepted.
+    pipeline = Pipeline.from_pretrained(
+        DIARIZATION_MODEL, use_auth_token=os.environ.get("HF_TOKEN")
+    )
+    if pipeline is None:
+        raise RuntimeError(
+            f"could not load {DIARIZATION_MODEL}: check that HF_TOKEN "
+            "is valid and that the model's gated conditions have been accepted "
+            "on Hugging Face"
+        )
+    turns = [
+        (turn.start, turn.end, speaker)
+        for turn, _, speaker in pipeline(audio_path).itertracks(yield_label=True)
+    ]
+
+    for segment in segments:
+        overlaps: Dict[str, float] = {}
+        for start, end, speaker in turns:
+            overlap = min(end, segment["end"]) - max(start, segment["start"])
+            if overlap > 0:
+                overlaps[speaker] = overlaps.get(speaker, 0.0) + overlap
+        if overlaps:
+            segment["speaker"] = max(overlaps, key=overlaps.get)
+
+
+def transcribe_audio(
+    audio_path: str, word_timestamps: bool = True, diarize: bool = False
+) -> int:
     """Transcribes an audio file using MLX Whisper and prints the result as JSON.
 
+    The JSON payload is preceded by a line containing only ``JSON_SENTINEL``.
+    Progress is reported on stderr as ``PROGRESS <0-1>`` lines. With
+    ``diarize``, decoding covers the first half of the range, and a
+    ``STAGE diarization`` line marks the start of the second.
+
     Args:
         audio_path: The path to the audio file.
+        word_timestamps: Whether to include a ``words`` list on each segment.
+            Word alignment adds an extra pass and may slow transcription.
+        diarize: Whether to label segments with a ``speaker`` via
+            ``assign_speakers``. If that fails, the transcription is still
+            printed, with the reason in a top-level ``diarization_error``.
 
     Returns:
         An exit code, 0 for success and 1 for failure.
@@ -27,25 +138,44 @@ def transcribe_audio(audio_path: str) -> int:
         stdout_capture = io.StringIO()
         stderr_capture = io.StringIO()
         
-        with redirect_stdout(stdout_capture), redirect_stderr(stderr_capture):
-            result = mlx_whisper.transcribe(
-                audio_path,
-                path_or_hf_repo="mlx-community/whisper-large-v3-turbo",
-                verbose=False,
-                word_timestamps=True,
-            )
-
-        segments: List[Dict[str, Union[int, float, str]]] = []
+        # Only mlx_whisper's own bar should report progress, so the swap is
+        # undone before anything else (e.g. diarization) creates a tqdm bar.
+        original_tqdm = tqdm.tqdm
+        tqdm.tqdm = ProgressBar
+        ProgressBar.span = DECODE_SPAN_WITH_DIARIZATION if diarize else 1.0
+        try:
+            with redirect_stdout(stdout_capture), redirect_stderr(stderr_capture):
+                result = mlx_whisper.transcribe(
+                    audio_path,
+                    path_or_hf_repo="mlx-community/whisper-large-v3-turbo",
+                    verbose=False,
+                    word_timestamps=word_timestamps,
+                )
+        finally:
+            tqdm.tqdm = original_tqdm
+
+        segments: List[Dict[str, Any]] = []
         if "segments" in result and result["segments"]:
             for i, segment in enumerate(result["segments"]):
-                segments.append(
-                    {
-                        "id": i,
-                        "start": float(segment.get("start", 0.0)),
-                        "end": float(segment.get("end", 0.0)),
-                        "text": str(segment.get("text", "")).strip(),
-                    }
-                )
+                entry: Dict[str, Any] = {
+                    "id": i,
+                    "start": float(segment.get("start", 0.0)),
+                    "end": float(segment.get("end", 0.0)),
+                    "text": str(segment.get("text", "")).strip(),
+                }
+                for key in ("avg_logprob", "no_speech_prob"):
+                    if segment.get(key) is not None:
+                        entry[key] = float(segment[key])
+                if word_timestamps:
+                    entry["words"] = [
+                        {
+                            "text": str(word.get("word", "")).strip(),
+                            "start": float(word.get("start", 0.0)),
+                            "end": float(word.get("end", 0.0)),
+                        }
+                        for word in segment.get("words", [])
+                    ]
+                segments.append(entry)
         else:
             segments = [
                 {
@@ -55,8 +185,28 @@ def transcribe_audio(audio_path: str) -> int:
                     "text": str(result.get("text", "")).strip(),
                 }
             ]
+            if word_timestamps:
+                segments[0]["words"] = []
+
+        output: Dict[str, Any] = {
+            "segments": segments,
+            "text": str(result.get("text", "")).strip(),
+        }
 
-        output = {"segments": segments, "text": str(result.get("text", "")).strip()}
+        # Diarization is optional: a failure here must not discard the
+        # finished transcription, so segments are returned without speakers
+        # and the reason is reported in "diarization_error".
+        if diarize:
+            print("STAGE diarization", file=sys.stderr, flush=True)
+            try:
+                with redirect_stdout(stdout_capture), redirect_stderr(stderr_capture):
+                    assign_speakers(audio_path, segments)
+            except Exception as e:
+                output["diarization_error"] = str(e)
+                print(f"WARNING diarization {e}", file=sys.stderr)
+            print("PROGRESS 1.00", file=sys.stderr, flush=True)
+
+        print(JSON_SENTINEL)
         print(json.dumps(output))
         return 0
 
@@ -67,13 +217,34 @@ def transcribe_audio(audio_path: str) -> int:
 
 
 if __name__ == "__main__":
-    if len(sys.argv) != 2:
-        print(f"Usage: python3 {sys.argv[0]} <audio_file>", file=sys.stderr)
-        sys.exit(1)
+    parser = argparse.ArgumentParser(description="Transcribe audio with MLX Whisper")
+    parser.add_argument("audio_file", help="Path to audio file")
+    parser.add_argument(
+        "--no-word-timestamps",
+        action="store_true",
+        help="Omit per-word timestamps (faster)",
+    )
+    parser.add_argument(
+        "--diarize",
+        action="store_true",
+        help="Label segments by speaker (requires pyannote.audio 3.x and HF_TOKEN)",
+    )
+    args = parser.parse_args()
 
-    audio_file = sys.argv[1]
-    if not os.path.exists(audio_file):
-        print(f"Audio file not found: {audio_file}", file=sys.stderr)
+    if not os.path.exists(args.audio_file):
+        print(f"Audio file not found: {args.audio_file}", file=sys.stderr)
         sys.exit(1)
 
-    sys.exit(transcribe_audio(audio_file)) 
+    if args.diarize:
+        problem = check_diarization_requirements()
+        if problem:
+            print(problem, file=sys.stderr)
+            sys.exit(1)
+
+    sys.exit(
+        transcribe_audio(
+            args.audio_file,
+            word_timestamps=not args.no_word_timestamps,
+            diarize=args.diarize,
+        )
+    ) 